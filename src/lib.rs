@@ -5,6 +5,7 @@
 //!   * `chain_err`
 //!   * non-verbose `Error`, `ErrorKind` pairs
 //!   * support for `bail!` and `ensure!` with custom `ErrorKind`-s
+//!   * `quick_main!` for `main` functions that return a `Result`
 //!
 //! The `failure` library recommends three different patterns for errors. This
 //! library implementes th complex one (and the most useful one) but without all the [boilerplate](https://boats.gitlab.io/failure/error-errorkind.html).
@@ -99,38 +100,99 @@
 //!     }
 //! }
 //! ```
-use failure::{Backtrace, Context, Fail};
+use failure::{Backtrace, Context};
 use std::fmt;
+use std::panic::Location;
+
+// Re-exported so that macros generated by this crate (e.g. `quick_main!`) can refer to
+// `$crate::Fail` without assuming the downstream binary has `failure` in scope under
+// that name.
+pub use failure::Fail;
 
 /// Trait which must be implemented by `ErrorKind`-s.
 ///
 /// The `Error` associated type should select between `UnboxedError<Self>` or `BoxedError<Self>`.
 pub trait ChainErrorKind: Fail + Sized {
-    type Error: Fail + From<Context<Self>>;
+    type Error: Fail + From<Context<Self>> + FromContext<Self>;
+}
+
+/// Builds a `ChainErrorKind::Error` from a `Context` plus an explicit `Location`.
+///
+/// `ResultExt`'s methods resolve `Location::caller()` themselves, before crossing any
+/// closure boundary, and hand it here explicitly. Relying on `From<Context<_>>`'s own
+/// `#[track_caller]` instead doesn't work: its direct caller is always failchain's
+/// internal `.into()`, never the user's `.chain_err`/`.chain_err_all` call site.
+pub trait FromContext<ErrorKindT: Fail>: Sized {
+    fn from_context(context: Context<ErrorKindT>, location: &'static Location<'static>) -> Self;
 }
 
 /// An error type which stores the backtrace, cause pointer and error kind inline.
 ///
 /// This is potentially a very large object, but it doesn't allocate on creation unlike
 /// `BoxedError`.
-#[derive(Debug)]
+///
+/// Its `Debug` implementation also records the `#[track_caller]` location of the site
+/// that created it, so even a `strip`-ped release binary prints a `file:line` for the
+/// error's origin, followed by the `Caused by:` chain.
 pub struct UnboxedError<ErrorKindT: Fail> {
     inner: Context<ErrorKindT>,
+    location: Option<&'static Location<'static>>,
 }
 
 /// An error type which stores the backtrace, cause pointer and error kind behind a `Box`.
 ///
 /// The size of this object is always one pointer. It's therefore smaller than `UnboxedError`, but
 /// requires an allocation when created.
-#[derive(Debug)]
+///
+/// Its `Debug` implementation also records the `#[track_caller]` location of the site
+/// that created it, so even a `strip`-ped release binary prints a `file:line` for the
+/// error's origin, followed by the `Caused by:` chain.
 pub struct BoxedError<ErrorKindT: Fail> {
     inner: Box<Context<ErrorKindT>>,
+    location: Option<&'static Location<'static>>,
 }
 
 impl<ErrorKindT: Fail> UnboxedError<ErrorKindT> {
     pub fn kind(&self) -> &ErrorKindT {
         self.inner.get_context()
     }
+
+    /// Returns an iterator over this error and every link in its cause chain, in order.
+    ///
+    /// The first item is always `self`; subsequent items are `self.cause()`,
+    /// `self.cause().cause()`, and so on until the chain runs out.
+    pub fn iter_causes(&self) -> impl Iterator<Item = &dyn Fail> {
+        std::iter::successors(Some(self as &dyn Fail), |fail| fail.cause())
+    }
+
+    /// Returns the first link in the cause chain (including `self`) that downcasts to `T`.
+    ///
+    /// This only matches bare `Fail` links, e.g. a foreign leaf error like `io::Error`.
+    /// A failchain `ErrorKind` `K` several `chain_err` calls deep is never a bare `T`
+    /// link itself — it's wrapped in a `BoxedError<K>`/`UnboxedError<K>` — so use
+    /// [`find_kind`](Self::find_kind) to look for that instead.
+    pub fn find_cause<T: Fail>(&self) -> Option<&T> {
+        self.iter_causes().find_map(|fail| fail.downcast_ref::<T>())
+    }
+
+    /// Returns the `ErrorKind` of the first link in the cause chain (including `self`)
+    /// that is a `BoxedError<K>` or `UnboxedError<K>`.
+    ///
+    /// Unlike `find_cause`, this unwraps failchain's own wrapper types, so it can
+    /// locate another crate's (or this error's own) `ErrorKind` nested several
+    /// `chain_err` calls deep.
+    pub fn find_kind<K: Fail>(&self) -> Option<&K> {
+        self.iter_causes().find_map(|fail| {
+            fail.downcast_ref::<BoxedError<K>>()
+                .map(BoxedError::kind)
+                .or_else(|| fail.downcast_ref::<UnboxedError<K>>().map(UnboxedError::kind))
+        })
+    }
+
+    /// Returns the deepest link in the cause chain.
+    pub fn root_cause(&self) -> &dyn Fail {
+        self.iter_causes().last().expect("self is always yielded")
+    }
 }
 
 /// Extension trait which adds the family of `.chain_err` methods to `Result` objects.
@@ -141,6 +203,7 @@ pub trait ResultExt: Sized {
     /// Replace the error in a Result with a new error built from `map`'s `ErrorKind` output.
     ///
     /// The original error is stored as the `cause`/`source` of the new one.
+    #[track_caller]
     fn chain_err<ErrorKindT: ChainErrorKind>(
         self,
         map: impl FnOnce() -> ErrorKindT,
@@ -149,23 +212,56 @@ pub trait ResultExt: Sized {
     }
 
     /// Like `chain_err`, but the callback is given an opportunity to inspect the original error.
+    #[track_caller]
     fn chain_inspect_err<ErrorKindT: ChainErrorKind>(
         self,
         map: impl FnOnce(&mut Self::Error) -> ErrorKindT,
     ) -> Result<Self::Success, ErrorKindT::Error>;
+
+    /// Like `chain_err`, but attaches one or more `ErrorKind` layers in one call,
+    /// producing a multi-layer cause chain from a single call site.
+    ///
+    /// `kind` wraps the original error; each kind yielded by `rest` then wraps the
+    /// previous one in turn. The last kind yielded by `rest` (or `kind` itself, if
+    /// `rest` is empty) ends up as the outermost kind, i.e. `self.kind()` on the
+    /// resulting error.
+    #[track_caller]
+    fn chain_err_all<ErrorKindT: ChainErrorKind>(
+        self,
+        kind: ErrorKindT,
+        rest: impl IntoIterator<Item = ErrorKindT>,
+    ) -> Result<Self::Success, ErrorKindT::Error>;
 }
 
 impl<SuccessT, ErrorT: Fail> ResultExt for Result<SuccessT, ErrorT> {
     type Success = SuccessT;
     type Error = ErrorT;
 
+    #[track_caller]
     fn chain_inspect_err<ErrorKindT: ChainErrorKind>(
         self,
         chain: impl FnOnce(&mut ErrorT) -> ErrorKindT,
     ) -> Result<Self::Success, ErrorKindT::Error> {
+        let location = Location::caller();
         self.map_err(|mut initial_error| {
             let kind = chain(&mut initial_error);
-            initial_error.context(kind).into()
+            ErrorKindT::Error::from_context(initial_error.context(kind), location)
+        })
+    }
+
+    #[track_caller]
+    fn chain_err_all<ErrorKindT: ChainErrorKind>(
+        self,
+        kind: ErrorKindT,
+        rest: impl IntoIterator<Item = ErrorKindT>,
+    ) -> Result<Self::Success, ErrorKindT::Error> {
+        let location = Location::caller();
+        self.map_err(|initial_error| {
+            let mut context = initial_error.context(kind);
+            for kind in rest {
+                context = context.context(kind);
+            }
+            ErrorKindT::Error::from_context(context, location)
         })
     }
 }
@@ -242,6 +338,46 @@ macro_rules! ensure {
     };
 }
 
+/// Wraps a fallible, `error_chain`-style entry point into a real `fn main()`.
+///
+/// On `Ok`, the process exits normally. On `Err`, it prints the full `Caused by:` cause
+/// chain (via the alternate `Display`, i.e. `{:#}`) and the backtrace if one was
+/// captured, then exits with code `1`.
+///
+/// Examples
+/// ---
+///
+/// ```rust,ignore
+/// fn run() -> Result<(), Error> {
+///     Ok(())
+/// }
+///
+/// quick_main!(run);
+/// ```
+///
+/// An explicit exit code can be given for the error case:
+///
+/// ```rust,ignore
+/// quick_main!(run, 2);
+/// ```
+#[macro_export]
+macro_rules! quick_main {
+    ($run:expr) => {
+        $crate::quick_main!($run, 1);
+    };
+    ($run:expr, $code:expr) => {
+        fn main() {
+            if let Err(error) = $run() {
+                eprintln!("{:#}", error);
+                if let Some(backtrace) = $crate::Fail::backtrace(&error) {
+                    eprintln!("{}", backtrace);
+                }
+                ::std::process::exit($code);
+            }
+        }
+    };
+}
+
 impl<ErrorKindT: Fail> Fail for UnboxedError<ErrorKindT> {
     fn cause(&self) -> Option<&Fail> {
         self.inner.cause()
@@ -254,19 +390,56 @@ impl<ErrorKindT: Fail> Fail for UnboxedError<ErrorKindT> {
 
 impl<ErrorKindT: Fail> fmt::Display for UnboxedError<ErrorKindT> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.inner.fmt(f)
+        if !f.alternate() {
+            return self.inner.fmt(f);
+        }
+        let mut causes = self.iter_causes();
+        write!(f, "{}", causes.next().expect("self is always yielded"))?;
+        for cause in causes {
+            write!(f, "\nCaused by:\n  {}", cause)?;
+        }
+        Ok(())
+    }
+}
+
+impl<ErrorKindT: Fail> fmt::Debug for UnboxedError<ErrorKindT> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some(location) => write!(f, "{}:{}: {}", location.file(), location.line(), self.kind())?,
+            None => write!(f, "{}", self.kind())?,
+        }
+        let mut cause = self.cause();
+        while let Some(fail) = cause {
+            write!(f, "\nCaused by:\n  {}", fail)?;
+            cause = fail.cause();
+        }
+        Ok(())
     }
 }
 
 impl<ErrorKindT: Fail> From<ErrorKindT> for UnboxedError<ErrorKindT> {
+    #[track_caller]
     fn from(kind: ErrorKindT) -> Self {
         Self::from(Context::new(kind))
     }
 }
 
 impl<ErrorKindT: Fail> From<Context<ErrorKindT>> for UnboxedError<ErrorKindT> {
+    #[track_caller]
     fn from(inner: Context<ErrorKindT>) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            location: Some(Location::caller()),
+        }
+    }
+}
+
+impl<ErrorKindT: Fail> FromContext<ErrorKindT> for UnboxedError<ErrorKindT> {
+    fn from_context(inner: Context<ErrorKindT>, location: &'static Location<'static>) -> Self {
+        Self {
+            inner,
+            location: Some(location),
+        }
     }
 }
 
@@ -274,6 +447,43 @@ impl<ErrorKindT: Fail> BoxedError<ErrorKindT> {
     pub fn kind(&self) -> &ErrorKindT {
         self.inner.get_context()
     }
+
+    /// Returns an iterator over this error and every link in its cause chain, in order.
+    ///
+    /// The first item is always `self`; subsequent items are `self.cause()`,
+    /// `self.cause().cause()`, and so on until the chain runs out.
+    pub fn iter_causes(&self) -> impl Iterator<Item = &dyn Fail> {
+        std::iter::successors(Some(self as &dyn Fail), |fail| fail.cause())
+    }
+
+    /// Returns the first link in the cause chain (including `self`) that downcasts to `T`.
+    ///
+    /// This only matches bare `Fail` links, e.g. a foreign leaf error like `io::Error`.
+    /// A failchain `ErrorKind` `K` several `chain_err` calls deep is never a bare `T`
+    /// link itself — it's wrapped in a `BoxedError<K>`/`UnboxedError<K>` — so use
+    /// [`find_kind`](Self::find_kind) to look for that instead.
+    pub fn find_cause<T: Fail>(&self) -> Option<&T> {
+        self.iter_causes().find_map(|fail| fail.downcast_ref::<T>())
+    }
+
+    /// Returns the `ErrorKind` of the first link in the cause chain (including `self`)
+    /// that is a `BoxedError<K>` or `UnboxedError<K>`.
+    ///
+    /// Unlike `find_cause`, this unwraps failchain's own wrapper types, so it can
+    /// locate another crate's (or this error's own) `ErrorKind` nested several
+    /// `chain_err` calls deep.
+    pub fn find_kind<K: Fail>(&self) -> Option<&K> {
+        self.iter_causes().find_map(|fail| {
+            fail.downcast_ref::<BoxedError<K>>()
+                .map(BoxedError::kind)
+                .or_else(|| fail.downcast_ref::<UnboxedError<K>>().map(UnboxedError::kind))
+        })
+    }
+
+    /// Returns the deepest link in the cause chain.
+    pub fn root_cause(&self) -> &dyn Fail {
+        self.iter_causes().last().expect("self is always yielded")
+    }
 }
 
 impl<ErrorKindT: Fail> Fail for BoxedError<ErrorKindT> {
@@ -288,20 +498,152 @@ impl<ErrorKindT: Fail> Fail for BoxedError<ErrorKindT> {
 
 impl<ErrorKindT: Fail> fmt::Display for BoxedError<ErrorKindT> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.inner.fmt(f)
+        if !f.alternate() {
+            return self.inner.fmt(f);
+        }
+        let mut causes = self.iter_causes();
+        write!(f, "{}", causes.next().expect("self is always yielded"))?;
+        for cause in causes {
+            write!(f, "\nCaused by:\n  {}", cause)?;
+        }
+        Ok(())
+    }
+}
+
+impl<ErrorKindT: Fail> fmt::Debug for BoxedError<ErrorKindT> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some(location) => write!(f, "{}:{}: {}", location.file(), location.line(), self.kind())?,
+            None => write!(f, "{}", self.kind())?,
+        }
+        let mut cause = self.cause();
+        while let Some(fail) = cause {
+            write!(f, "\nCaused by:\n  {}", fail)?;
+            cause = fail.cause();
+        }
+        Ok(())
     }
 }
 
 impl<ErrorKindT: Fail> From<ErrorKindT> for BoxedError<ErrorKindT> {
+    #[track_caller]
     fn from(kind: ErrorKindT) -> Self {
         Self::from(Context::new(kind))
     }
 }
 
 impl<ErrorKindT: Fail> From<Context<ErrorKindT>> for BoxedError<ErrorKindT> {
+    #[track_caller]
     fn from(inner: Context<ErrorKindT>) -> Self {
         Self {
             inner: Box::new(inner),
+            location: Some(Location::caller()),
+        }
+    }
+}
+
+impl<ErrorKindT: Fail> FromContext<ErrorKindT> for BoxedError<ErrorKindT> {
+    fn from_context(inner: Context<ErrorKindT>, location: &'static Location<'static>) -> Self {
+        Self {
+            inner: Box::new(inner),
+            location: Some(location),
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[derive(Clone, Eq, PartialEq, Debug, Fail)]
+    enum LowKind {
+        #[fail(display = "low")]
+        Low,
+        #[fail(display = "mid")]
+        Mid,
+    }
+
+    impl ChainErrorKind for LowKind {
+        type Error = BoxedError<LowKind>;
+    }
+
+    #[derive(Clone, Eq, PartialEq, Debug, Fail)]
+    enum HighKind {
+        #[fail(display = "high")]
+        High,
+    }
+
+    impl ChainErrorKind for HighKind {
+        type Error = BoxedError<HighKind>;
+    }
+
+    fn io_err() -> Result<(), io::Error> {
+        Err(io::Error::new(io::ErrorKind::Other, "boom"))
+    }
+
+    fn low() -> Result<(), BoxedError<LowKind>> {
+        io_err().chain_err(|| LowKind::Low)
+    }
+
+    fn high() -> Result<(), BoxedError<HighKind>> {
+        low().chain_err(|| HighKind::High)
+    }
+
+    #[test]
+    fn iter_causes_yields_self_then_chain_in_order() {
+        let err = low().unwrap_err();
+        let rendered: Vec<String> = err.iter_causes().map(ToString::to_string).collect();
+        assert_eq!(rendered, vec!["low".to_string(), "boom".to_string()]);
+    }
+
+    #[test]
+    fn plain_display_is_unchanged() {
+        let err = high().unwrap_err();
+        assert_eq!(format!("{}", err), "high");
+    }
+
+    #[test]
+    fn alternate_display_renders_full_cause_chain() {
+        let err = high().unwrap_err();
+        assert_eq!(format!("{:#}", err), "high\nCaused by:\n  low\nCaused by:\n  boom");
+    }
+
+    #[test]
+    fn find_cause_locates_a_bare_fail_link() {
+        let err = low().unwrap_err();
+        assert!(err.find_cause::<io::Error>().is_some());
+    }
+
+    #[test]
+    fn find_cause_does_not_unwrap_failchain_kinds() {
+        let err = high().unwrap_err();
+        assert!(err.find_cause::<LowKind>().is_none());
+        assert!(err.find_cause::<BoxedError<LowKind>>().is_some());
+    }
+
+    #[test]
+    fn find_kind_unwraps_a_nested_failchain_kind() {
+        let err = high().unwrap_err();
+        assert_eq!(err.find_kind::<LowKind>(), Some(&LowKind::Low));
+    }
+
+    #[test]
+    fn root_cause_is_the_deepest_link() {
+        let err = high().unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "boom");
+    }
+
+    #[test]
+    fn chain_err_all_wraps_each_kind_in_order() {
+        let err = io_err()
+            .chain_err_all(LowKind::Low, vec![LowKind::Mid])
+            .unwrap_err();
+        assert_eq!(*err.kind(), LowKind::Mid);
+        assert_eq!(
+            format!("{:#}", err),
+            "mid\nCaused by:\n  low\nCaused by:\n  boom"
+        );
+    }
+}